@@ -0,0 +1,107 @@
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::storage::StorageError;
+
+/// Every way a request can fail, centralized so handlers return
+/// `Result<Json<T>, ApiError>` and use `?` instead of building a
+/// `(StatusCode, Json<Error>)` tuple at each call site.
+#[derive(Debug)]
+pub enum ApiError {
+    ClientNotFound,
+    MalformedId,
+    InvalidTipo,
+    InvalidValor,
+    InvalidDescricao,
+    LimitExceeded,
+    RateLimited,
+    PayloadTooLarge,
+    MalformedBody,
+    Internal,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    erro: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, erro) = match self {
+            ApiError::ClientNotFound | ApiError::MalformedId => {
+                (StatusCode::NOT_FOUND, "Id inválido")
+            }
+            ApiError::InvalidTipo => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Tipo inválido. Precisa ser \"c\" ou \"d\"",
+            ),
+            ApiError::InvalidValor => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Valor deve ser positivo e maior do que zero",
+            ),
+            ApiError::InvalidDescricao => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Descrição precisa estar entre 1 e 10 caracteres.",
+            ),
+            ApiError::LimitExceeded => (StatusCode::UNPROCESSABLE_ENTITY, "erro"),
+            ApiError::RateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Limite de requisições excedido",
+            ),
+            ApiError::PayloadTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Corpo da requisição excede o tamanho máximo permitido",
+            ),
+            ApiError::MalformedBody => (StatusCode::UNPROCESSABLE_ENTITY, "Corpo da requisição inválido"),
+            ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Erro interno"),
+        };
+
+        (
+            status,
+            Json(ErrorBody {
+                erro: erro.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::ClientNotFound => ApiError::ClientNotFound,
+            StorageError::LimitExceeded => ApiError::LimitExceeded,
+            StorageError::Db(_) => ApiError::Internal,
+        }
+    }
+}
+
+/// Drop-in for `axum::Json` that rejects through `ApiError` instead of
+/// axum's default plain-text rejection body, so a body over the route's
+/// `DefaultBodyLimit` or malformed JSON still comes back as our `{ "erro":
+/// ... }` shape.
+pub struct AppJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for AppJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| match rejection {
+                JsonRejection::BytesRejection(_) => ApiError::PayloadTooLarge,
+                _ => ApiError::MalformedBody,
+            })?;
+
+        Ok(AppJson(value))
+    }
+}
@@ -1,10 +1,17 @@
 #![allow(clippy::inconsistent_digit_grouping)]
 
+mod error;
+mod rate_limit;
+mod sse;
+mod storage;
+
+use std::env;
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Path, State},
     http::StatusCode,
+    middleware,
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
@@ -12,10 +19,29 @@ use axum::{
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use tokio::sync::Mutex;
+use error::{ApiError, AppJson};
+use rate_limit::{InMemoryRateLimiter, PgRateLimiter, RateLimiter};
+use sse::TransactionBus;
+use storage::{PgStorage, Storage};
+
+/// `descricao` is capped at 10 chars, so a well-formed `transacoes` body
+/// never comes close to this; anything bigger is either abuse or a mistake.
+const TRANSACAO_BODY_LIMIT_BYTES: usize = 512;
+
+/// The fixed set of clients this challenge seeds (same ids `InMemoryStorage`
+/// hard-codes), shared by `RateLimiter` and `TransactionBus` so both shard
+/// their per-client state over the same known range.
+const KNOWN_CLIENT_IDS: std::ops::RangeInclusive<i64> = 1..=5;
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    storage: Arc<dyn Storage>,
+    limiter: Arc<dyn RateLimiter>,
+    bus: TransactionBus,
+}
 
 #[derive(Debug, Clone)]
-struct Client {
+pub(crate) struct Client {
     id: i64,
     limite: i64,
     saldo: i64,
@@ -42,30 +68,11 @@ impl Client {
 }
 
 #[derive(Serialize)]
-struct TransactionOkResp {
+pub(crate) struct TransactionOkResp {
     limite: i64,
     saldo: i64,
 }
 
-#[derive(Clone)]
-struct ApiState {
-    client_list: Vec<Client>,
-}
-
-impl ApiState {
-    fn new() -> Self {
-        let client_list = vec![
-            Client::new(1, 1_000__00, 0),
-            Client::new(2, 800__00, 0),
-            Client::new(3, 10_000__00, 0),
-            Client::new(4, 100_000__00, 0),
-            Client::new(5, 5_000__00, 0),
-        ];
-
-        Self { client_list }
-    }
-}
-
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct TransactionRequest {
     valor: i64,
@@ -73,177 +80,69 @@ struct TransactionRequest {
     descricao: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-struct Transaction {
+#[derive(Deserialize, Serialize, Debug, Clone, sqlx::FromRow)]
+pub(crate) struct Transaction {
     valor: i64,
     tipo: String,
     descricao: String,
     realizada_em: DateTime<Utc>,
 }
 
-#[derive(Serialize)]
-struct Error {
-    erro: String,
-}
-
 async fn client_transaction(
     Path(client_id): Path<String>,
-    State(state): State<Arc<Mutex<ApiState>>>,
-    Json(payload): Json<TransactionRequest>,
-) -> (StatusCode, Result<Json<TransactionOkResp>, Json<Error>>) {
-    let mut state = state.lock().await;
-
-    let client_ids = state
-        .client_list
-        .clone()
-        .into_iter()
-        .map(|x| x.id)
-        .collect::<Vec<_>>();
-
-    match client_ids.contains(&client_id.parse().unwrap()) {
-        true => {
-            let target_id = client_id.clone().parse::<i64>().unwrap();
-            let target_client = state
-                .client_list
-                .iter_mut()
-                .find(|client| client.id == target_id)
-                .unwrap();
-
-            let operation = &payload.tipo;
-            let value = payload.valor;
-
-            if payload.tipo != "c" && payload.tipo != "d" {
-                return (
-                    StatusCode::UNPROCESSABLE_ENTITY,
-                    Err(Json(Error {
-                        erro: String::from("Tipo inválido. Precisa ser \"c\" ou \"d\""),
-                    })),
-                );
-            }
+    State(state): State<AppState>,
+    AppJson(payload): AppJson<TransactionRequest>,
+) -> Result<Json<TransactionOkResp>, ApiError> {
+    let client_id = client_id.parse::<i64>().map_err(|_| ApiError::MalformedId)?;
 
-            if payload.valor <= 0 {
-                return (
-                    StatusCode::UNPROCESSABLE_ENTITY,
-                    Err(Json(Error {
-                        erro: String::from("Valor deve ser positivo e maior do que zero"),
-                    })),
-                );
-            }
-
-            if !(payload.descricao.chars().count() > 0 && payload.descricao.chars().count() <= 10) {
-                return (
-                    StatusCode::UNPROCESSABLE_ENTITY,
-                    Err(Json(Error {
-                        erro: String::from("Descrição precisa estar entre 1 e 10 caracteres."),
-                    })),
-                );
-            }
-
-            let current_balance = target_client.saldo;
-            let limit = target_client.limite;
-
-            let future_value = match operation.as_str() {
-                "c" => current_balance + value,
-                "d" => current_balance - value,
-                _ => current_balance, // TODO: processing error could be here... also... this should be an enum
-            };
-
-            if future_value < (0 - limit) {
-                return (
-                    StatusCode::UNPROCESSABLE_ENTITY,
-                    Err(Json(Error {
-                        erro: String::from("erro"),
-                    })),
-                );
-            }
+    if payload.tipo != "c" && payload.tipo != "d" {
+        return Err(ApiError::InvalidTipo);
+    }
 
-            target_client.add_transacao(Transaction {
-                valor: payload.valor,
-                tipo: payload.tipo,
-                descricao: payload.descricao,
-                realizada_em: Utc::now(),
-            });
-
-            target_client.update_saldo(future_value);
-
-            (
-                StatusCode::OK,
-                Ok(Json(TransactionOkResp {
-                    limite: target_client.limite,
-                    saldo: target_client.saldo,
-                })),
-            )
-        }
+    if payload.valor <= 0 {
+        return Err(ApiError::InvalidValor);
+    }
 
-        false => (
-            StatusCode::NOT_FOUND,
-            Err(Json(Error {
-                erro: String::from("Id inválido"),
-            })),
-        ),
+    if !(payload.descricao.chars().count() > 0 && payload.descricao.chars().count() <= 10) {
+        return Err(ApiError::InvalidDescricao);
     }
+
+    let applied = state
+        .storage
+        .apply_transaction(
+            client_id,
+            payload.valor,
+            &payload.tipo,
+            &payload.descricao,
+            &state.bus,
+        )
+        .await?;
+
+    Ok(Json(applied.resp))
 }
 
 #[derive(Serialize)]
-struct ClientBalanceSaldo {
+pub(crate) struct ClientBalanceSaldo {
     total: i64,
     data_extrato: DateTime<Utc>,
     limite: i64,
 }
 
 #[derive(Serialize)]
-struct ClientBalanceResponse {
+pub(crate) struct ClientBalanceResponse {
     saldo: ClientBalanceSaldo,
     ultimas_transacoes: Vec<Transaction>,
 }
 
 async fn client_balance(
     Path(client_id): Path<String>,
-    State(state): State<Arc<Mutex<ApiState>>>,
-) -> (StatusCode, Result<Json<ClientBalanceResponse>, Json<Error>>) {
-    let mut state = state.lock().await;
-
-    let client_ids = state
-        .client_list
-        .clone()
-        .into_iter()
-        .map(|x| x.id)
-        .collect::<Vec<_>>();
-
-    match client_ids.contains(&client_id.parse().unwrap()) {
-        true => {
-            let target_id = client_id.clone().parse::<i64>().unwrap();
-            let target_client = state
-                .client_list
-                .iter_mut()
-                .find(|client| client.id == target_id)
-                .unwrap();
-
-            (
-                StatusCode::OK,
-                Ok(Json(ClientBalanceResponse {
-                    saldo: ClientBalanceSaldo {
-                        total: target_client.saldo,
-                        data_extrato: Utc::now(),
-                        limite: target_client.limite,
-                    },
-                    ultimas_transacoes: target_client
-                        .transacoes
-                        .iter()
-                        .rev()
-                        .take(10)
-                        .cloned()
-                        .collect(),
-                })),
-            )
-        }
-        false => (
-            StatusCode::NOT_FOUND,
-            Err(Json(Error {
-                erro: String::from("Id inválido"),
-            })),
-        ),
-    }
+    State(state): State<AppState>,
+) -> Result<Json<ClientBalanceResponse>, ApiError> {
+    let client_id = client_id.parse::<i64>().map_err(|_| ApiError::MalformedId)?;
+
+    let resp = state.storage.fetch_extrato(client_id).await?;
+
+    Ok(Json(resp))
 }
 
 async fn root() -> impl IntoResponse {
@@ -252,12 +151,55 @@ async fn root() -> impl IntoResponse {
 
 #[tokio::main]
 async fn main() {
-    let state = Arc::new(Mutex::new(ApiState::new()));
+    // Postgres-backed storage and rate limiting only matter together: both
+    // exist so more than one replica can sit behind a load balancer, so
+    // whichever one gets a pool, the other should too.
+    let (storage, limiter, pg_pool): (Arc<dyn Storage>, Arc<dyn RateLimiter>, Option<sqlx::PgPool>) =
+        match env::var("DATABASE_URL") {
+            Ok(database_url) => {
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .max_connections(20)
+                    .connect(&database_url)
+                    .await
+                    .expect("failed to connect to Postgres");
+
+                (
+                    Arc::new(PgStorage::new(pool.clone())),
+                    Arc::new(PgRateLimiter::new(pool.clone())),
+                    Some(pool),
+                )
+            }
+            Err(_) => (
+                Arc::new(storage::InMemoryStorage::new()),
+                Arc::new(InMemoryRateLimiter::new(KNOWN_CLIENT_IDS.count())),
+                None,
+            ),
+        };
+
+    let state = AppState {
+        storage,
+        limiter,
+        bus: TransactionBus::new(KNOWN_CLIENT_IDS),
+    };
+
+    // With a shared Postgres backend, also listen for transactions other
+    // replicas applied so this replica's SSE subscribers hear about them too.
+    if let Some(pool) = pg_pool {
+        sse::listen_for_transactions(pool, state.bus.clone());
+    }
+
+    let transacoes = post(client_transaction)
+        .layer(DefaultBodyLimit::max(TRANSACAO_BODY_LIMIT_BYTES))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit::rate_limit));
 
     let app = Router::new()
         .route("/", get(root))
-        .route("/clientes/:client_id/transacoes", post(client_transaction))
+        .route("/clientes/:client_id/transacoes", transacoes)
         .route("/clientes/:client_id/extrato", get(client_balance))
+        .route(
+            "/clientes/:client_id/transacoes/stream",
+            get(sse::transaction_stream),
+        )
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:9999").await.unwrap();
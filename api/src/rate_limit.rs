@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+
+use crate::{error::ApiError, AppState};
+
+const REFILL_PER_SECOND: f64 = 10.0;
+const BURST_CAPACITY: f64 = 20.0;
+
+/// Outcome of checking a client's bucket: either the id isn't one of the
+/// known clients (no bucket to check; the middleware rejects it with 404),
+/// or it is, carrying whether the request is allowed plus the state for the
+/// `X-RateLimit-*` headers.
+pub enum RateLimitOutcome {
+    Unknown,
+    /// The backend couldn't be reached; fail open rather than take the
+    /// whole API down over the rate limiter.
+    Error,
+    Checked {
+        allowed: bool,
+        remaining: u64,
+        reset: u64,
+    },
+}
+
+/// Per-client token-bucket limiting, independent of whether the bucket
+/// state lives in this process or in Postgres — the same split `storage.rs`
+/// makes between `InMemoryStorage` and `PgStorage`, and for the same reason:
+/// a single-process `HashMap`/array only limits a single replica, so running
+/// more than one instance behind a load balancer (the scenario `PgStorage`
+/// was built for) needs the bucket shared too.
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    async fn check(&self, client_id: i64) -> RateLimitOutcome;
+}
+
+/// Derives the remaining-token count and the seconds until a token is
+/// available again (0 if one already is) from the tokens left in a bucket
+/// after a take attempt. Shared by `Bucket::try_take` and
+/// `PgRateLimiter::check` so the two backends can't drift apart on what
+/// `X-RateLimit-Remaining`/`X-RateLimit-Reset` mean for the same bucket state.
+fn remaining_and_reset(tokens: f64) -> (u64, u64) {
+    let reset = if tokens >= 1.0 {
+        0
+    } else {
+        ((1.0 - tokens) / REFILL_PER_SECOND).ceil() as u64
+    };
+
+    (tokens.max(0.0) as u64, reset)
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new() -> Self {
+        Self {
+            tokens: BURST_CAPACITY,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * REFILL_PER_SECOND).min(BURST_CAPACITY);
+        self.last_refill = now;
+    }
+
+    /// Refills, then takes one token if available. Returns whether the
+    /// request is allowed, the tokens remaining, and the seconds until a
+    /// token is available again (0 if one already is).
+    fn try_take(&mut self) -> (bool, u64, u64) {
+        self.refill();
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        let (remaining, reset) = remaining_and_reset(self.tokens);
+        (allowed, remaining, reset)
+    }
+}
+
+/// One bucket per known client id, indexed like `storage.rs` shards
+/// `Client`s, so a flood of requests against ids outside that fixed set
+/// can't grow this forever, and clients don't contend on a shared lock.
+/// Only correct for a single replica — see `RateLimiter`'s doc comment.
+pub struct InMemoryRateLimiter {
+    buckets: Arc<[Mutex<Bucket>]>,
+}
+
+impl InMemoryRateLimiter {
+    pub fn new(num_clients: usize) -> Self {
+        let buckets = (0..num_clients).map(|_| Mutex::new(Bucket::new())).collect();
+
+        Self { buckets }
+    }
+
+    fn slot(&self, client_id: i64) -> Option<&Mutex<Bucket>> {
+        let index = usize::try_from(client_id.checked_sub(1)?).ok()?;
+        self.buckets.get(index)
+    }
+}
+
+#[async_trait]
+impl RateLimiter for InMemoryRateLimiter {
+    async fn check(&self, client_id: i64) -> RateLimitOutcome {
+        let Some(slot) = self.slot(client_id) else {
+            return RateLimitOutcome::Unknown;
+        };
+
+        let (allowed, remaining, reset) = slot.lock().await.try_take();
+        RateLimitOutcome::Checked {
+            allowed,
+            remaining,
+            reset,
+        }
+    }
+}
+
+/// Postgres-backed `RateLimiter`, for running more than one replica behind a
+/// load balancer. The refill-then-take is a single `UPDATE ... FROM`
+/// statement keyed on `cliente_id`, the same conditional-update trick
+/// `PgStorage::apply_transaction` uses for the balance-limit check, so two
+/// replicas racing a request for the same client still only ever take one
+/// token per tick. Assumes a pre-seeded `rate_limit_buckets` table, one row
+/// per known client id, the same way `PgStorage` assumes a pre-seeded
+/// `clientes` table.
+pub struct PgRateLimiter {
+    pool: PgPool,
+}
+
+impl PgRateLimiter {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for PgRateLimiter {
+    async fn check(&self, client_id: i64) -> RateLimitOutcome {
+        let row = sqlx::query_as::<_, (bool, f64)>(
+            r#"
+            WITH refreshed AS (
+                SELECT cliente_id,
+                       LEAST($2, tokens + EXTRACT(EPOCH FROM (now() - last_refill)) * $3) AS tokens
+                  FROM rate_limit_buckets
+                 WHERE cliente_id = $1
+                   FOR UPDATE
+            )
+            UPDATE rate_limit_buckets AS b
+               SET tokens = CASE WHEN refreshed.tokens >= 1 THEN refreshed.tokens - 1 ELSE refreshed.tokens END,
+                   last_refill = now()
+              FROM refreshed
+             WHERE b.cliente_id = refreshed.cliente_id
+         RETURNING refreshed.tokens >= 1, b.tokens
+            "#,
+        )
+        .bind(client_id)
+        .bind(BURST_CAPACITY)
+        .bind(REFILL_PER_SECOND)
+        .fetch_optional(&self.pool)
+        .await;
+
+        let (allowed, tokens) = match row {
+            // Unknown client id: no bucket row, the middleware will 404 it.
+            Ok(None) => return RateLimitOutcome::Unknown,
+            Ok(Some(row)) => row,
+            Err(_) => return RateLimitOutcome::Error,
+        };
+
+        let (remaining, reset) = remaining_and_reset(tokens);
+
+        RateLimitOutcome::Checked {
+            allowed,
+            remaining,
+            reset,
+        }
+    }
+}
+
+/// Axum middleware that rate-limits by the `client_id` path segment and
+/// attaches `X-RateLimit-*` headers to every response for a client whose
+/// bucket was checked, success or 429, so clients can see how much headroom
+/// they have left. Responses for an id that doesn't parse, an unknown id, or
+/// a limiter backend error carry no `X-RateLimit-*` headers.
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Ok(client_id) = client_id.parse::<i64>() else {
+        return next.run(request).await;
+    };
+
+    let (allowed, remaining, reset) = match state.limiter.check(client_id).await {
+        RateLimitOutcome::Unknown => {
+            // Not one of the known client ids: reject here rather than
+            // letting an unbounded flood of bogus ids through unthrottled.
+            return ApiError::ClientNotFound.into_response();
+        }
+        RateLimitOutcome::Error => return next.run(request).await,
+        RateLimitOutcome::Checked {
+            allowed,
+            remaining,
+            reset,
+        } => (allowed, remaining, reset),
+    };
+
+    let mut response = if allowed {
+        next.run(request).await
+    } else {
+        ApiError::RateLimited.into_response()
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(
+        "x-ratelimit-limit",
+        HeaderValue::from_str(&(BURST_CAPACITY as u64).to_string()).unwrap(),
+    );
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(reset));
+
+    response
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgListener, PgPool};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+use crate::{error::ApiError, AppState, Transaction};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+/// Postgres channel `PgStorage` notifies on and `listen_for_transactions`
+/// subscribes to, so a transaction applied by *any* replica reaches SSE
+/// subscribers connected to *this* replica, not just the one that applied it.
+pub const NOTIFY_CHANNEL: &str = "transacoes";
+
+/// What `PgStorage::apply_transaction` sends as the `NOTIFY` payload and
+/// `listen_for_transactions` parses back out.
+#[derive(Serialize, Deserialize)]
+pub struct NotifyPayload {
+    pub cliente_id: i64,
+    pub transaction: Transaction,
+}
+
+/// Fan-out hub for newly applied transactions: one `broadcast` channel per
+/// known client id, so `client_transaction` and the SSE handler don't need
+/// to share anything beyond this.
+///
+/// On its own this only sees transactions applied by the process holding
+/// this `TransactionBus` — fine for a single instance backed by
+/// `InMemoryStorage`. When running against `PgStorage` with more than one
+/// replica, pair it with `listen_for_transactions` so every replica's bus
+/// also learns about transactions the others applied.
+#[derive(Clone)]
+pub struct TransactionBus {
+    channels: Arc<HashMap<i64, broadcast::Sender<Transaction>>>,
+}
+
+impl TransactionBus {
+    pub fn new(client_ids: impl IntoIterator<Item = i64>) -> Self {
+        let channels = client_ids
+            .into_iter()
+            .map(|id| (id, broadcast::channel(CHANNEL_CAPACITY).0))
+            .collect();
+
+        Self {
+            channels: Arc::new(channels),
+        }
+    }
+
+    /// No receivers subscribed yet is a normal state, not an error.
+    pub fn publish(&self, client_id: i64, transaction: Transaction) {
+        if let Some(sender) = self.channels.get(&client_id) {
+            let _ = sender.send(transaction);
+        }
+    }
+
+    fn subscribe(&self, client_id: i64) -> Option<broadcast::Receiver<Transaction>> {
+        self.channels.get(&client_id).map(|sender| sender.subscribe())
+    }
+}
+
+pub async fn transaction_stream(
+    Path(client_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let client_id = client_id.parse::<i64>().map_err(|_| ApiError::MalformedId)?;
+
+    let receiver = state
+        .bus
+        .subscribe(client_id)
+        .ok_or(ApiError::ClientNotFound)?;
+
+    let stream = BroadcastStream::new(receiver)
+        .filter_map(|transaction| transaction.ok())
+        .map(|transaction| {
+            Ok(Event::default()
+                .json_data(&transaction)
+                .expect("Transaction always serializes"))
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Spawns a background task that `LISTEN`s on [`NOTIFY_CHANNEL`] and
+/// republishes every notification into `bus`, so transactions applied by
+/// other replicas still reach SSE subscribers connected to this one.
+/// `PgStorage::apply_transaction` is the other half: it `NOTIFY`s this
+/// channel from inside the same transaction that writes the row, so nothing
+/// is ever announced that didn't actually commit.
+///
+/// A dropped connection (Postgres restart, network blip) reconnects after
+/// [`RECONNECT_DELAY`] rather than giving up for good — this replica's own
+/// writes only ever reach its SSE subscribers through this listener (see
+/// the `Storage` trait's doc comment), so letting it die silently
+/// would leave that replica's streams permanently empty.
+pub fn listen_for_transactions(pool: PgPool, bus: TransactionBus) {
+    tokio::spawn(async move {
+        loop {
+            let mut listener = match PgListener::connect_with(&pool).await {
+                Ok(listener) => listener,
+                Err(_) => {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            if listener.listen(NOTIFY_CHANNEL).await.is_err() {
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+
+            loop {
+                let Ok(notification) = listener.recv().await else {
+                    break;
+                };
+
+                if let Ok(payload) = serde_json::from_str::<NotifyPayload>(notification.payload())
+                {
+                    bus.publish(payload.cliente_id, payload.transaction);
+                }
+            }
+
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
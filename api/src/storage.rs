@@ -0,0 +1,349 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+
+use crate::sse::{NotifyPayload, TransactionBus, NOTIFY_CHANNEL};
+use crate::{Client, ClientBalanceResponse, ClientBalanceSaldo, Transaction, TransactionOkResp};
+
+/// Errors that can arise while reading or writing client state, independent of
+/// how that state is actually stored.
+#[derive(Debug)]
+pub enum StorageError {
+    ClientNotFound,
+    LimitExceeded,
+    Db(sqlx::Error),
+}
+
+impl From<sqlx::Error> for StorageError {
+    fn from(err: sqlx::Error) -> Self {
+        StorageError::Db(err)
+    }
+}
+
+/// What a successful `apply_transaction` hands back: the response body for
+/// the caller.
+pub struct AppliedTransaction {
+    pub resp: TransactionOkResp,
+}
+
+/// Everything the handlers need from wherever client balances and
+/// transactions actually live, so `client_transaction`/`client_balance` don't
+/// care whether that's a `Vec` in memory or a row in Postgres.
+///
+/// `apply_transaction` takes the `TransactionBus` so each backend can publish
+/// the transaction it just wrote the way that's actually correct for it:
+/// `InMemoryStorage` has no other way to reach the bus, so it publishes
+/// directly; `PgStorage` doesn't need to, since its own replica's
+/// `listen_for_transactions` task hears the `NOTIFY` it just sent and
+/// publishes from there. Publishing from both places would double-deliver
+/// every transaction to the writing replica's own SSE subscribers.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn apply_transaction(
+        &self,
+        client_id: i64,
+        valor: i64,
+        tipo: &str,
+        descricao: &str,
+        bus: &TransactionBus,
+    ) -> Result<AppliedTransaction, StorageError>;
+
+    async fn fetch_extrato(&self, client_id: i64) -> Result<ClientBalanceResponse, StorageError>;
+}
+
+/// Clients behind per-client `RwLock`s, indexed by `id - 1`, so a write to
+/// client 1 never blocks a read or write against client 5. Each entry still
+/// serializes its own writes, which is what keeps the limit check and
+/// `update_saldo` atomic.
+pub struct InMemoryStorage {
+    clients: Box<[RwLock<Client>]>,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        let clients = vec![
+            Client::new(1, 1_000__00, 0),
+            Client::new(2, 800__00, 0),
+            Client::new(3, 10_000__00, 0),
+            Client::new(4, 100_000__00, 0),
+            Client::new(5, 5_000__00, 0),
+        ]
+        .into_iter()
+        .map(RwLock::new)
+        .collect();
+
+        Self { clients }
+    }
+
+    fn slot(&self, client_id: i64) -> Option<&RwLock<Client>> {
+        let index = usize::try_from(client_id.checked_sub(1)?).ok()?;
+        self.clients.get(index)
+    }
+}
+
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Storage for InMemoryStorage {
+    async fn apply_transaction(
+        &self,
+        client_id: i64,
+        valor: i64,
+        tipo: &str,
+        descricao: &str,
+        bus: &TransactionBus,
+    ) -> Result<AppliedTransaction, StorageError> {
+        let slot = self.slot(client_id).ok_or(StorageError::ClientNotFound)?;
+        let mut target_client = slot.write().await;
+
+        let future_value = match tipo {
+            "c" => target_client.saldo + valor,
+            "d" => target_client.saldo - valor,
+            _ => target_client.saldo,
+        };
+
+        if future_value < (0 - target_client.limite) {
+            return Err(StorageError::LimitExceeded);
+        }
+
+        let transaction = Transaction {
+            valor,
+            tipo: tipo.to_string(),
+            descricao: descricao.to_string(),
+            realizada_em: Utc::now(),
+        };
+        target_client.add_transacao(transaction.clone());
+        target_client.update_saldo(future_value);
+
+        let resp = TransactionOkResp {
+            limite: target_client.limite,
+            saldo: target_client.saldo,
+        };
+
+        bus.publish(client_id, transaction);
+
+        Ok(AppliedTransaction { resp })
+    }
+
+    async fn fetch_extrato(&self, client_id: i64) -> Result<ClientBalanceResponse, StorageError> {
+        let slot = self.slot(client_id).ok_or(StorageError::ClientNotFound)?;
+        let target_client = slot.read().await;
+
+        Ok(ClientBalanceResponse {
+            saldo: ClientBalanceSaldo {
+                total: target_client.saldo,
+                data_extrato: Utc::now(),
+                limite: target_client.limite,
+            },
+            ultimas_transacoes: target_client
+                .transacoes
+                .iter()
+                .rev()
+                .take(10)
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// Postgres-backed `Storage`. The balance-limit check happens inside a single
+/// `UPDATE ... WHERE saldo + :valor >= -limite` so concurrent debits against
+/// the same client can't both pass a check done in application code and then
+/// both write — the database only lets the statement succeed if the
+/// resulting balance still respects the limit.
+pub struct PgStorage {
+    pool: PgPool,
+}
+
+impl PgStorage {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn apply_transaction(
+        &self,
+        client_id: i64,
+        valor: i64,
+        tipo: &str,
+        descricao: &str,
+        // Not used here: the NOTIFY below round-trips back through this same
+        // replica's own `listen_for_transactions` listener, which publishes
+        // to the bus from there instead.
+        _bus: &TransactionBus,
+    ) -> Result<AppliedTransaction, StorageError> {
+        let signed_valor = if tipo == "d" { -valor } else { valor };
+
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            UPDATE clientes
+               SET saldo = saldo + $1
+             WHERE id = $2
+               AND saldo + $1 >= -limite
+         RETURNING saldo, limite
+            "#,
+        )
+        .bind(signed_valor)
+        .bind(client_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let (saldo, limite) = match row {
+            Some(row) => row,
+            None => {
+                // Either the client doesn't exist or the update's WHERE
+                // clause rejected it for breaching the limit; tell them apart
+                // with a cheap follow-up read.
+                let exists: Option<(i64,)> =
+                    sqlx::query_as("SELECT limite FROM clientes WHERE id = $1")
+                        .bind(client_id)
+                        .fetch_optional(&mut *tx)
+                        .await?;
+
+                return Err(match exists {
+                    Some(_) => StorageError::LimitExceeded,
+                    None => StorageError::ClientNotFound,
+                });
+            }
+        };
+
+        let realizada_em = Utc::now();
+
+        sqlx::query(
+            r#"
+            INSERT INTO transacoes (cliente_id, valor, tipo, descricao, realizada_em)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(client_id)
+        .bind(valor)
+        .bind(tipo)
+        .bind(descricao)
+        .bind(realizada_em)
+        .execute(&mut *tx)
+        .await?;
+
+        let transaction = Transaction {
+            valor,
+            tipo: tipo.to_string(),
+            descricao: descricao.to_string(),
+            realizada_em,
+        };
+
+        // NOTIFY from inside the transaction so it's only ever delivered
+        // once this commits, and so every replica's SSE bus (not just the
+        // one that handled this request) hears about it — see
+        // `sse::listen_for_transactions`.
+        let payload = NotifyPayload {
+            cliente_id: client_id,
+            transaction: transaction.clone(),
+        };
+        if let Ok(payload) = serde_json::to_string(&payload) {
+            sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(NOTIFY_CHANNEL)
+                .bind(payload)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(AppliedTransaction {
+            resp: TransactionOkResp { limite, saldo },
+        })
+    }
+
+    async fn fetch_extrato(&self, client_id: i64) -> Result<ClientBalanceResponse, StorageError> {
+        let client = sqlx::query_as::<_, (i64, i64)>("SELECT saldo, limite FROM clientes WHERE id = $1")
+            .bind(client_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(StorageError::ClientNotFound)?;
+
+        let transacoes = sqlx::query_as::<_, Transaction>(
+            r#"
+            SELECT valor, tipo, descricao, realizada_em
+              FROM transacoes
+             WHERE cliente_id = $1
+          ORDER BY realizada_em DESC
+             LIMIT 10
+            "#,
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ClientBalanceResponse {
+            saldo: ClientBalanceSaldo {
+                total: client.0,
+                data_extrato: Utc::now(),
+                limite: client.1,
+            },
+            ultimas_transacoes: transacoes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Client 1 starts at `saldo = 0` with `limite = 1_000_00`. Firing more
+    /// concurrent debits than the limit allows checks that each client's
+    /// writes are still serialized by its `RwLock`, not just fast individually
+    /// — if the limit check and the write weren't atomic, enough of these
+    /// racing together could push `saldo` past `-limite`.
+    #[tokio::test]
+    async fn concurrent_debits_never_breach_limit() {
+        let storage = Arc::new(InMemoryStorage::new());
+        let bus = TransactionBus::new(1..=5);
+        let client_id = 1;
+        let limite = 1_000__00;
+        let valor = 3_000;
+
+        let mut handles = Vec::new();
+        for _ in 0..100 {
+            let storage = Arc::clone(&storage);
+            let bus = bus.clone();
+            handles.push(tokio::spawn(async move {
+                storage
+                    .apply_transaction(client_id, valor, "d", "debito", &bus)
+                    .await
+            }));
+        }
+
+        for handle in handles {
+            let _ = handle.await.unwrap();
+        }
+
+        let extrato = storage.fetch_extrato(client_id).await.unwrap();
+        assert!(extrato.saldo.total >= -limite);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_ids_are_rejected_not_panics() {
+        let storage = InMemoryStorage::new();
+        let bus = TransactionBus::new(1..=5);
+
+        for client_id in [0, -1, i64::MIN] {
+            let result = storage
+                .apply_transaction(client_id, 1, "c", "teste", &bus)
+                .await;
+            assert!(matches!(result, Err(StorageError::ClientNotFound)));
+
+            let result = storage.fetch_extrato(client_id).await;
+            assert!(matches!(result, Err(StorageError::ClientNotFound)));
+        }
+    }
+}